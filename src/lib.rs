@@ -1,10 +1,22 @@
-use rs_merkle::{algorithms::Sha256, MerkleProof, MerkleTree};
 use sha2::Digest;
 use std::collections::{BTreeMap, HashMap, HashSet};
-use std::sync::{Arc, RwLock, RwLockWriteGuard};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock, RwLock, RwLockWriteGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+mod storage;
+
+pub use storage::{Database, MemoryDatabase, NodeKey, PatchSet, RocksDbDatabase};
 
 pub type Hash = [u8; 32];
 
+/// Depth of the sparse Merkle tree, in bits of `sha256(key)`.
+const TREE_DEPTH: u16 = 256;
+
+/// Canonical hash of an absent leaf.
+const EMPTY_LEAF: Hash = [0u8; 32];
+
 pub fn hash_kv(key: &str, value: &str) -> Hash {
     let mut hasher = sha2::Sha256::new();
     hasher.update(key.as_bytes());
@@ -12,36 +24,358 @@ pub fn hash_kv(key: &str, value: &str) -> Hash {
     hasher.finalize().into()
 }
 
+pub fn hash_key(key: &str) -> Hash {
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(key.as_bytes());
+    hasher.finalize().into()
+}
+
+fn hash_pair(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// The namespace used by the flat `Transaction::put`/`get` API, which is
+/// sugar for `put_in`/`get_in` against this namespace.
+pub const DEFAULT_NAMESPACE: &str = "";
+
+/// Identifies which sparse Merkle tree a node belongs to: the top-level
+/// tree of per-namespace roots, or one namespace's own subtree of keys.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum TreeId {
+    Top,
+    Namespace(Hash),
+}
+
+/// Hashes a namespace into the top-level tree's leaf address for it.
+pub fn hash_namespace(namespace: &str) -> Hash {
+    hash_key(namespace)
+}
+
+/// Builds the backend key for `(namespace, key)`, keeping namespaces from
+/// colliding in the flat value store the way `hash_namespace` keeps them
+/// from colliding in the tree. Length-prefixed rather than separator-joined,
+/// since `namespace`/`key` are arbitrary strings that may themselves contain
+/// any separator byte we could pick.
+fn composite_key(namespace: &str, key: &str) -> String {
+    format!("{}:{namespace}{key}", namespace.len())
+}
+
+/// `empty_hashes()[i]` is the root of an empty subtree of height `i`
+/// (`empty_hashes()[0] == EMPTY_LEAF`). Computed once and shared by every tree.
+fn empty_hashes() -> &'static [Hash; TREE_DEPTH as usize + 1] {
+    static TABLE: OnceLock<[Hash; TREE_DEPTH as usize + 1]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [EMPTY_LEAF; TREE_DEPTH as usize + 1];
+        for level in 1..=TREE_DEPTH as usize {
+            table[level] = hash_pair(&table[level - 1], &table[level - 1]);
+        }
+        table
+    })
+}
+
+/// Returns the bit of `path` at `index`, counting up from bit 0 at the leaf
+/// (the least-significant bit of the last byte) towards bit 255 at the root.
+fn get_bit(path: &Hash, index: u16) -> bool {
+    let byte = 31 - (index / 8) as usize;
+    let shift = index % 8;
+    (path[byte] >> shift) & 1 == 1
+}
+
+fn flip_bit(path: &Hash, index: u16) -> Hash {
+    let mut out = *path;
+    let byte = 31 - (index / 8) as usize;
+    let shift = index % 8;
+    out[byte] ^= 1 << shift;
+    out
+}
+
+/// Zeroes out the lowest `level` bits of `path`, leaving the prefix that
+/// identifies the node at that level.
+fn mask_path(path: &Hash, level: u16) -> Hash {
+    let mut out = *path;
+    for index in 0..level {
+        let byte = 31 - (index / 8) as usize;
+        let shift = index % 8;
+        out[byte] &= !(1 << shift);
+    }
+    out
+}
+
+/// Recomputes the root that `leaf` at `key_hash` implies, given its sibling
+/// path from the leaf up to the root. Used both to rehash on commit and to
+/// check proofs.
+fn root_from_path(key_hash: &Hash, leaf: Hash, siblings: &[Hash]) -> Hash {
+    let mut current = leaf;
+    for (level, sibling) in siblings.iter().enumerate() {
+        let level = level as u16;
+        current = if get_bit(key_hash, level) {
+            hash_pair(sibling, &current)
+        } else {
+            hash_pair(&current, sibling)
+        };
+    }
+    current
+}
+
+type TreeNodeKey = (u16, Hash);
+
+/// Rehashes the authentication path of every `(key_hash, leaf_hash)` pair and
+/// returns the rehashed nodes plus the resulting root, consulting `node_at`
+/// for a sibling only once nothing fresher is already in the diff.
+fn compute_diff(
+    leaves: &[(Hash, Hash)],
+    node_at: impl Fn(u16, &Hash) -> Hash,
+) -> (HashMap<TreeNodeKey, Hash>, Hash) {
+    let mut diff: HashMap<TreeNodeKey, Hash> = HashMap::new();
+
+    for &(key_hash, leaf_hash) in leaves {
+        let mut current = leaf_hash;
+        for level in 0..TREE_DEPTH {
+            diff.insert((level, mask_path(&key_hash, level)), current);
+            let sibling_path = flip_bit(&key_hash, level);
+            let sibling = diff
+                .get(&(level, mask_path(&sibling_path, level)))
+                .copied()
+                .unwrap_or_else(|| node_at(level, &sibling_path));
+            current = if get_bit(&key_hash, level) {
+                hash_pair(&sibling, &current)
+            } else {
+                hash_pair(&current, &sibling)
+            };
+        }
+        diff.insert((TREE_DEPTH, EMPTY_LEAF), current);
+    }
+
+    let new_root = diff
+        .get(&(TREE_DEPTH, EMPTY_LEAF))
+        .copied()
+        .unwrap_or_else(|| node_at(TREE_DEPTH, &EMPTY_LEAF));
+    (diff, new_root)
+}
+
+/// In-RAM record of every historical patch a `DB` has committed, used to
+/// answer [`VerifiableDB::proof_at`] for a retained version. Not itself
+/// persisted, so it starts empty on every process restart regardless of
+/// backend — the backend only ever needs to hold the latest version's nodes.
+#[derive(Default)]
+struct VersionHistory {
+    base: HashMap<NodeKey, Hash>,
+    base_version: u64,
+    /// Per-node history, so `try_node_at` only ever scans the versions that
+    /// actually touched the node it's looking for instead of every patch
+    /// ever recorded.
+    by_node: HashMap<NodeKey, BTreeMap<u64, Hash>>,
+    roots: BTreeMap<u64, Hash>,
+}
+
+impl VersionHistory {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn current_version(&self) -> u64 {
+        self.roots.keys().next_back().copied().unwrap_or(0)
+    }
+
+    fn try_node_at(&self, tree: TreeId, version: u64, level: u16, path: &Hash) -> Option<Hash> {
+        let key = (tree, level, mask_path(path, level));
+        if let Some(versions) = self.by_node.get(&key) {
+            if let Some((_, hash)) = versions.range(..=version).next_back() {
+                return Some(*hash);
+            }
+        }
+        self.base.get(&key).copied()
+    }
+
+    fn record(&mut self, version: u64, diff: HashMap<NodeKey, Hash>, root: Hash) {
+        self.roots.insert(version, root);
+        for (key, hash) in diff {
+            self.by_node.entry(key).or_default().insert(version, hash);
+        }
+    }
+
+    /// Folds every patch older than `min_retained_version` into `base`,
+    /// reclaiming their memory. Versions below `min_retained_version` can no
+    /// longer be proved afterwards; `roots` is kept in full since it is cheap.
+    fn prune_to(&mut self, min_retained_version: u64) {
+        if min_retained_version == 0 || min_retained_version <= self.base_version + 1 {
+            return;
+        }
+        let base = &mut self.base;
+        self.by_node.retain(|key, versions| {
+            let retained = versions.split_off(&min_retained_version);
+            if let Some((_, hash)) = versions.iter().next_back() {
+                base.insert(*key, *hash);
+            }
+            *versions = retained;
+            !versions.is_empty()
+        });
+        self.base_version = min_retained_version - 1;
+    }
+}
+
+/// Whether an entry's pre-state leaf was absent or occupied at `Proof::old_root`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EntryKind {
+    /// `key` had no value before this commit (`old_leaf == EMPTY_LEAF`).
+    Insert,
+    /// `key` already had a value before this commit.
+    Overwrite,
+}
+
+/// Authentication path proving `old_leaf` was the value at `key` within
+/// `namespace`'s subtree, plus the top-level path proving that subtree's
+/// root (`old_namespace_root`) was the namespace's leaf under `Proof::old_root`.
+///
+/// `siblings`/`namespace_siblings` are queried against `Proof::old_version`
+/// and `new_siblings`/`new_namespace_siblings` against `Proof::new_version`,
+/// rather than reusing one path for both: when a commit writes two or more
+/// keys that share a Merkle-tree ancestor, that ancestor's hash differs
+/// before and after the commit, so a single pre-commit path can't also be
+/// valid for recomputing the post-commit root.
+pub struct KeyProof {
+    pub namespace: String,
+    pub key: String,
+    pub kind: EntryKind,
+    pub old_leaf: Hash,
+    pub siblings: Vec<Hash>,
+    pub old_namespace_root: Hash,
+    pub namespace_siblings: Vec<Hash>,
+    pub new_siblings: Vec<Hash>,
+    pub new_namespace_siblings: Vec<Hash>,
+}
+
 pub struct Proof {
+    pub old_version: u64,
+    pub new_version: u64,
     pub old_root: Hash,
     pub new_root: Hash,
-    pub total_leaves_old: usize,
-    pub affected_indices: Vec<usize>,
-    pub pre_state_proof: MerkleProof<Sha256>,
+    pub entries: Vec<KeyProof>,
+}
+
+/// A membership or absence proof against a historical root, produced by
+/// [`VerifiableDB::proof_at`]/[`VerifiableDB::proof_at_in`]. Unlike [`Proof`],
+/// this only claims the state of one key at one retained version, not a
+/// transition between two.
+pub struct HistoricalProof {
+    pub version: u64,
+    pub root: Hash,
+    pub namespace: String,
+    pub key: String,
+    pub leaf: Hash,
+    pub siblings: Vec<Hash>,
+    pub namespace_root: Hash,
+    pub namespace_siblings: Vec<Hash>,
+}
+
+impl HistoricalProof {
+    pub fn is_valid(&self) -> bool {
+        let namespace_root = root_from_path(&hash_key(&self.key), self.leaf, &self.siblings);
+        if namespace_root != self.namespace_root {
+            return false;
+        }
+        root_from_path(&hash_namespace(&self.namespace), self.namespace_root, &self.namespace_siblings) == self.root
+    }
+
+    /// True if this is a membership proof (`key` has a value); false if it
+    /// proves `key`'s absence instead (`leaf == EMPTY_LEAF`). Either way,
+    /// `is_valid` checks the same authentication path.
+    pub fn is_membership(&self) -> bool {
+        self.leaf != EMPTY_LEAF
+    }
 }
 
 struct DB {
-    data: BTreeMap<String, String>,
-    tree: MerkleTree<Sha256>,
+    backend: Box<dyn Database>,
+    history: VersionHistory,
 }
 
 impl DB {
-    fn new() -> Self {
-        Self {
-            data: BTreeMap::new(),
-            tree: MerkleTree::<Sha256>::new(),
+    fn new(backend: Box<dyn Database>) -> Self {
+        // A reopened backend only ever persists the latest version's nodes,
+        // so this in-RAM history can't answer for anything at or before
+        // whatever version it resumes at, same as if that range had been
+        // pruned.
+        let mut history = VersionHistory::new();
+        history.base_version = backend.current_version();
+        Self { backend, history }
+    }
+
+    /// The latest version this `DB` knows about, whether recorded in-RAM
+    /// this process or recovered from a durable backend on reopen.
+    fn current_version(&self) -> u64 {
+        self.history.current_version().max(self.backend.current_version())
+    }
+
+    fn root_at(&self, tree: TreeId, version: u64) -> Hash {
+        self.node_at(tree, version, TREE_DEPTH, &EMPTY_LEAF)
+    }
+
+    /// Looks up a node of `tree` as of `version`: the in-RAM history is
+    /// authoritative for anything committed this process, and the backend is
+    /// consulted only as the latest-version fallback (it has no notion of
+    /// older versions).
+    fn node_at(&self, tree: TreeId, version: u64, level: u16, path: &Hash) -> Hash {
+        if let Some(hash) = self.history.try_node_at(tree, version, level, path) {
+            return hash;
+        }
+        if version == self.backend.current_version() {
+            if let Some(hash) = self.backend.get_node((tree, level, mask_path(path, level))) {
+                return hash;
+            }
         }
+        empty_hashes()[level as usize]
     }
 
-    fn refresh_tree(&mut self) {
-        if self.data.is_empty() {
-            self.tree = MerkleTree::<Sha256>::new();
-            return;
+    /// Returns the leaf hash at `key_hash` in `tree` as of `version`, and its
+    /// 256-entry sibling path from the leaf up to that tree's root.
+    fn path_for(&self, tree: TreeId, version: u64, key_hash: &Hash) -> (Hash, Vec<Hash>) {
+        let leaf = self.node_at(tree, version, 0, key_hash);
+        let siblings = (0..TREE_DEPTH)
+            .map(|level| self.node_at(tree, version, level, &flip_bit(key_hash, level)))
+            .collect();
+        (leaf, siblings)
+    }
+
+    /// Applies `writes` (grouped by namespace) on top of the current version:
+    /// recomputes each touched namespace's own subtree, then the one
+    /// top-level path per touched namespace, durably through the backend and
+    /// in-RAM through the version history. Returns `(new_version, new_root)`
+    /// where `new_root` is the top-level (global) root.
+    fn commit(&mut self, writes: &[(String, String, String)]) -> (u64, Hash) {
+        let base_version = self.current_version();
+
+        let mut by_namespace: HashMap<&str, Vec<(Hash, Hash)>> = HashMap::new();
+        for (namespace, key, value) in writes {
+            by_namespace.entry(namespace.as_str()).or_default().push((hash_key(key), hash_kv(key, value)));
         }
-        let leaves: Vec<Hash> = self.data.iter()
-            .map(|(k, v)| hash_kv(k, v))
+
+        let mut nodes: HashMap<NodeKey, Hash> = HashMap::new();
+        let mut namespace_leaves = Vec::with_capacity(by_namespace.len());
+        for (namespace, leaves) in by_namespace {
+            let namespace_hash = hash_namespace(namespace);
+            let tree = TreeId::Namespace(namespace_hash);
+            let (diff, new_namespace_root) = compute_diff(&leaves, |level, path| self.node_at(tree, base_version, level, path));
+            nodes.extend(diff.into_iter().map(|((level, path), hash)| ((tree, level, path), hash)));
+            namespace_leaves.push((namespace_hash, new_namespace_root));
+        }
+
+        let (top_diff, new_root) = compute_diff(&namespace_leaves, |level, path| self.node_at(TreeId::Top, base_version, level, path));
+        nodes.extend(top_diff.into_iter().map(|((level, path), hash)| ((TreeId::Top, level, path), hash)));
+
+        let values = writes
+            .iter()
+            .map(|(namespace, key, value)| (composite_key(namespace, key), value.clone()))
             .collect();
-        self.tree = MerkleTree::<Sha256>::from_leaves(&leaves);
+
+        let version = base_version + 1;
+        self.history.record(version, nodes.clone(), new_root);
+        self.backend.apply_patch(PatchSet { version, values, nodes });
+
+        (version, new_root)
     }
 }
 
@@ -52,138 +386,390 @@ pub struct VerifiableDB {
 }
 
 impl VerifiableDB {
-    pub fn new(verify_txn: bool) -> Self {
-        Self { state: Arc::new(RwLock::new(DB::new())), verify_txn }
+    /// Builds a `VerifiableDB` over the given storage backend, e.g.
+    /// [`MemoryDatabase`] for speed or [`RocksDbDatabase`] for durability.
+    pub fn new(verify_txn: bool, backend: Box<dyn Database>) -> Self {
+        Self { state: Arc::new(RwLock::new(DB::new(backend))), verify_txn }
+    }
+
+    /// Convenience constructor for the common case of an in-memory backend.
+    pub fn in_memory(verify_txn: bool) -> Self {
+        Self::new(verify_txn, Box::new(MemoryDatabase::new()))
     }
 
     pub fn begin(&self) -> Transaction<'_> {
         let guard = self.state.write().unwrap();
-        let old_root = guard.tree.root().unwrap_or([0u8; 32]);
+        let old_version = guard.current_version();
+        let old_root = guard.root_at(TreeId::Top, old_version);
 
         Transaction {
             guard: guard,
-            performed_reads: HashMap::new(),
-            pending_writes: HashMap::new(),
+            overlays: vec![Overlay::default()],
+            old_version,
             old_root,
             verify_txn: self.verify_txn,
         }
     }
+
+    pub fn get_db_size(&self) -> usize {
+        self.state.read().unwrap().backend.len()
+    }
+
+    pub fn latest_version(&self) -> u64 {
+        self.state.read().unwrap().current_version()
+    }
+
+    /// Produces a membership proof (an absence proof if `key` has no value)
+    /// for `key` in [`DEFAULT_NAMESPACE`] against the root retained at
+    /// `version`. Sugar for [`Self::proof_at_in`].
+    pub fn proof_at(&self, version: u64, key: &str) -> Option<HistoricalProof> {
+        self.proof_at_in(version, DEFAULT_NAMESPACE, key)
+    }
+
+    /// Produces a membership proof (an absence proof if `key` has no value)
+    /// for `key` in `namespace` against the root retained at `version`, or
+    /// `None` if that version was never committed,
+    /// has since been folded away by a [`Pruner`], or predates this process's
+    /// history (e.g. right after a [`RocksDbDatabase`] reopen, which only
+    /// persists the latest version's nodes).
+    pub fn proof_at_in(&self, version: u64, namespace: &str, key: &str) -> Option<HistoricalProof> {
+        let guard = self.state.read().unwrap();
+        let current = guard.current_version();
+        if version > current {
+            return None;
+        }
+        // `version == current` is always answerable straight from the
+        // backend's latest-node fallback, even if the history can't
+        // otherwise account for anything this old.
+        if version <= guard.history.base_version && version != current {
+            return None;
+        }
+        let namespace_hash = hash_namespace(namespace);
+        let key_hash = hash_key(key);
+        let (leaf, siblings) = guard.path_for(TreeId::Namespace(namespace_hash), version, &key_hash);
+        let (namespace_root, namespace_siblings) = guard.path_for(TreeId::Top, version, &namespace_hash);
+        Some(HistoricalProof {
+            version,
+            root: guard.root_at(TreeId::Top, version),
+            namespace: namespace.to_string(),
+            key: key.to_string(),
+            leaf,
+            siblings,
+            namespace_root,
+            namespace_siblings,
+        })
+    }
+
+    /// Proves that `key` currently has a value in [`DEFAULT_NAMESPACE`].
+    /// Returns `None` if `key` is absent — see [`Self::prove_absence`].
+    pub fn prove_membership(&self, key: &str) -> Option<HistoricalProof> {
+        self.prove_membership_in(DEFAULT_NAMESPACE, key)
+    }
+
+    /// Namespaced form of [`Self::prove_membership`].
+    pub fn prove_membership_in(&self, namespace: &str, key: &str) -> Option<HistoricalProof> {
+        let proof = self.proof_at_in(self.latest_version(), namespace, key)?;
+        proof.is_membership().then_some(proof)
+    }
+
+    /// Proves that `key` currently has no value in [`DEFAULT_NAMESPACE`].
+    pub fn prove_absence(&self, key: &str) -> Option<HistoricalProof> {
+        self.prove_absence_in(DEFAULT_NAMESPACE, key)
+    }
+
+    /// Namespaced form of [`Self::prove_absence`].
+    pub fn prove_absence_in(&self, namespace: &str, key: &str) -> Option<HistoricalProof> {
+        let proof = self.proof_at_in(self.latest_version(), namespace, key)?;
+        (!proof.is_membership()).then_some(proof)
+    }
+}
+
+/// A write or read is identified by its namespace and key; `DEFAULT_NAMESPACE`
+/// is used for the flat `put`/`get` API.
+pub type NamespacedKey = (String, String);
+
+/// One frame of a transaction's savepoint stack. Frame 0 is the base frame
+/// and always present.
+#[derive(Default)]
+struct Overlay {
+    writes: HashMap<NamespacedKey, String>,
+    reads: HashMap<NamespacedKey, String>,
+}
+
+/// A handle returned by [`Transaction::savepoint`]. Must be released or
+/// rolled back in reverse creation order (innermost first); releasing or
+/// rolling back an outer savepoint first invalidates handles for any
+/// savepoint created after it.
+pub struct SavepointId(usize);
+
+/// An affected entry's pre-commit state, captured by [`Transaction::commit`]
+/// before the batch is applied and turned into a [`KeyProof`] once the
+/// post-commit siblings are available too.
+struct PendingEntry {
+    namespace: String,
+    key: String,
+    kind: EntryKind,
+    old_leaf: Hash,
+    siblings: Vec<Hash>,
+    old_namespace_root: Hash,
+    namespace_siblings: Vec<Hash>,
 }
 
 pub struct Transaction<'a> {
     guard: RwLockWriteGuard<'a, DB>,
-    performed_reads: HashMap<String, String>,
-    pending_writes: HashMap<String, String>,
+    overlays: Vec<Overlay>,
+    old_version: u64,
     old_root: Hash,
     verify_txn: bool,
 }
 
 impl<'a> Transaction<'a> {
     pub fn get(&mut self, key: &str) -> Option<String> {
-        if let Some(val) = self.pending_writes.get(key) {
-            return Some(val.clone());
+        self.get_in(DEFAULT_NAMESPACE, key)
+    }
+
+    pub fn put(&mut self, key: &str, value: &str) {
+        self.put_in(DEFAULT_NAMESPACE, key, value);
+    }
+
+    pub fn get_in(&mut self, namespace: &str, key: &str) -> Option<String> {
+        let id = (namespace.to_string(), key.to_string());
+        for overlay in self.overlays.iter().rev() {
+            if let Some(val) = overlay.writes.get(&id) {
+                return Some(val.clone());
+            }
         }
 
-        let val = self.guard.data.get(key).cloned();
+        let val = self.guard.backend.get_value(&composite_key(namespace, key));
         if let Some(ref v) = val {
-            self.performed_reads.insert(key.to_string(), v.clone());
+            self.overlays.last_mut().unwrap().reads.insert(id, v.clone());
         }
         val
     }
 
-    pub fn put(&mut self, key: &str, value: &str) {
-        self.pending_writes.insert(key.to_string(), value.to_string());
+    pub fn put_in(&mut self, namespace: &str, key: &str, value: &str) {
+        self.overlays
+            .last_mut()
+            .unwrap()
+            .writes
+            .insert((namespace.to_string(), key.to_string()), value.to_string());
     }
 
-    pub fn commit(mut self) -> Option<Proof> {
-        if self.verify_txn {
-            let total_leaves_old = self.guard.data.len();
+    /// Pushes a new overlay frame; writes and reads land there until it is
+    /// rolled back or released.
+    pub fn savepoint(&mut self) -> SavepointId {
+        self.overlays.push(Overlay::default());
+        SavepointId(self.overlays.len() - 1)
+    }
 
-            let mut affected_keys = HashSet::new();
-            for k in self.performed_reads.keys() {
-                affected_keys.insert(k.clone());
-            }
-            for k in self.pending_writes.keys() {
-                affected_keys.insert(k.clone());
-            }
+    /// Discards every write and read performed since `savepoint`, excluding
+    /// the discarded reads from the final commit [`Proof`].
+    pub fn rollback_to(&mut self, savepoint: SavepointId) {
+        self.overlays.truncate(savepoint.0);
+    }
 
-            let mut affected_indices = Vec::new();
-            for (i, (k, _)) in self.guard.data.iter().enumerate() {
-                if affected_keys.contains(k) {
-                    affected_indices.push(i);
-                }
-            }
+    /// Merges `savepoint`'s frame into the one beneath it, keeping its
+    /// writes and reads but no longer tracking them separately.
+    pub fn release(&mut self, savepoint: SavepointId) {
+        if savepoint.0 == 0 || savepoint.0 >= self.overlays.len() {
+            return;
+        }
+        let frame = self.overlays.remove(savepoint.0);
+        let under = &mut self.overlays[savepoint.0 - 1];
+        under.reads.extend(frame.reads);
+        under.writes.extend(frame.writes);
+    }
 
-            let pre_state_proof = self.guard.tree.proof(&affected_indices);
+    pub fn commit(self) -> Option<Proof> {
+        let mut pending_writes: HashMap<NamespacedKey, String> = HashMap::new();
+        let mut performed_reads: HashMap<NamespacedKey, String> = HashMap::new();
+        for overlay in &self.overlays {
+            performed_reads.extend(overlay.reads.clone());
+            pending_writes.extend(overlay.writes.clone());
+        }
 
-            for (k, v) in &self.pending_writes {
-                self.guard.data.insert(k.clone(), v.clone());
-            }
+        let mut guard = self.guard;
+        let writes: Vec<(String, String, String)> = pending_writes
+            .iter()
+            .map(|((namespace, key), value)| (namespace.clone(), key.clone(), value.clone()))
+            .collect();
+
+        if self.verify_txn {
+            let mut affected_keys: HashSet<NamespacedKey> = HashSet::new();
+            affected_keys.extend(performed_reads.keys().cloned());
+            affected_keys.extend(pending_writes.keys().cloned());
+
+            // Capture each entry's pre-commit path before applying the
+            // batch, then its post-commit path after: siblings shared with
+            // another key written in this same commit change hash between
+            // the two, so neither loop of `verify_secure_update` can be fed
+            // from a single path.
+            let pre: Vec<PendingEntry> = affected_keys
+                .into_iter()
+                .map(|(namespace, key)| {
+                    let namespace_hash = hash_namespace(&namespace);
+                    let key_hash = hash_key(&key);
+                    let (old_leaf, siblings) = guard.path_for(TreeId::Namespace(namespace_hash), self.old_version, &key_hash);
+                    let (old_namespace_root, namespace_siblings) =
+                        guard.path_for(TreeId::Top, self.old_version, &namespace_hash);
+                    let kind = if old_leaf == EMPTY_LEAF { EntryKind::Insert } else { EntryKind::Overwrite };
+                    PendingEntry { namespace, key, kind, old_leaf, siblings, old_namespace_root, namespace_siblings }
+                })
+                .collect();
+
+            let (new_version, new_root) = guard.commit(&writes);
+
+            let entries: Vec<KeyProof> = pre
+                .into_iter()
+                .map(|pending| {
+                    let namespace_hash = hash_namespace(&pending.namespace);
+                    let key_hash = hash_key(&pending.key);
+                    let (_, new_siblings) = guard.path_for(TreeId::Namespace(namespace_hash), new_version, &key_hash);
+                    let (_, new_namespace_siblings) = guard.path_for(TreeId::Top, new_version, &namespace_hash);
+                    KeyProof {
+                        namespace: pending.namespace,
+                        key: pending.key,
+                        kind: pending.kind,
+                        old_leaf: pending.old_leaf,
+                        siblings: pending.siblings,
+                        old_namespace_root: pending.old_namespace_root,
+                        namespace_siblings: pending.namespace_siblings,
+                        new_siblings,
+                        new_namespace_siblings,
+                    }
+                })
+                .collect();
 
-            self.guard.refresh_tree();
-            let new_root = self.guard.tree.root().unwrap_or([0u8; 32]);
             Some(Proof {
+                old_version: self.old_version,
+                new_version,
                 old_root: self.old_root,
                 new_root,
-                total_leaves_old,
-                affected_indices,
-                pre_state_proof,
+                entries,
             })
         } else {
-            for (k, v) in &self.pending_writes {
-                self.guard.data.insert(k.clone(), v.clone());
-            }
+            guard.commit(&writes);
             None
         }
     }
 }
 
+/// Verifies each entry's old state, then its namespace's old root, then that
+/// namespace root's place under `proof.old_root` (and likewise for the new
+/// side). `entry.kind` pins down which check applies instead of inferring it
+/// from `old_state`.
+///
+/// `old_state`/`new_state` are keyed by `(namespace, key)`, not bare `key`:
+/// a single commit can touch the same key name in two different namespaces
+/// (e.g. `"balance"` in both `"acct1"` and `"acct2"`), and those are
+/// unrelated leaves that need independent claimed values.
 pub fn verify_secure_update(
     proof: &Proof,
-    old_state: &HashMap<String, String>,
-    new_state: &HashMap<String, String>,
+    old_state: &HashMap<NamespacedKey, String>,
+    new_state: &HashMap<NamespacedKey, String>,
 ) -> bool {
-    let mut sorted_keys: Vec<&String> = old_state.keys().collect();
-    sorted_keys.sort();
+    for entry in &proof.entries {
+        let id = (entry.namespace.clone(), entry.key.clone());
+        let expected_old_leaf = match (entry.kind, old_state.get(&id)) {
+            (EntryKind::Insert, None) => EMPTY_LEAF,
+            (EntryKind::Overwrite, Some(val)) => hash_kv(&entry.key, val),
+            _ => {
+                println!(
+                    "Security Alert: claimed old state does not match proof kind for key '{}' in namespace '{}'.",
+                    entry.key, entry.namespace
+                );
+                return false;
+            }
+        };
+        if expected_old_leaf != entry.old_leaf {
+            println!(
+                "Security Alert: claimed old state does not match proof for key '{}' in namespace '{}'.",
+                entry.key, entry.namespace
+            );
+            return false;
+        }
 
-    let old_leaves: Vec<Hash> = sorted_keys.iter()
-        .map(|k| {
-            let val = old_state.get(*k).expect("Key missing in old state");
-            hash_kv(k, val)
-        })
-        .collect();
+        let key_hash = hash_key(&entry.key);
+        let computed_namespace_root = root_from_path(&key_hash, entry.old_leaf, &entry.siblings);
+        if computed_namespace_root != entry.old_namespace_root {
+            println!("Security Alert: Pre-state proof invalid for key '{}'.", entry.key);
+            return false;
+        }
+
+        let namespace_hash = hash_namespace(&entry.namespace);
+        let computed_old_root = root_from_path(&namespace_hash, entry.old_namespace_root, &entry.namespace_siblings);
+        if computed_old_root != proof.old_root {
+            println!("Security Alert: Pre-state proof invalid for namespace '{}'.", entry.namespace);
+            return false;
+        }
+    }
 
-    let read_ok = proof.pre_state_proof.verify(
-        proof.old_root,
-        &proof.affected_indices,
-        &old_leaves,
-        proof.total_leaves_old,
-    );
+    for entry in &proof.entries {
+        let id = (entry.namespace.clone(), entry.key.clone());
+        let key_hash = hash_key(&entry.key);
+        let new_leaf = match new_state.get(&id).or_else(|| old_state.get(&id)) {
+            Some(val) => hash_kv(&entry.key, val),
+            None => EMPTY_LEAF,
+        };
+        let new_namespace_root = root_from_path(&key_hash, new_leaf, &entry.new_siblings);
 
-    if !read_ok {
-        println!("Security Alert: Pre-state proof invalid.");
-        return false;
+        let namespace_hash = hash_namespace(&entry.namespace);
+        let computed_new_root = root_from_path(&namespace_hash, new_namespace_root, &entry.new_namespace_siblings);
+        if computed_new_root != proof.new_root {
+            return false;
+        }
     }
 
-    let new_leaves: Vec<Hash> = sorted_keys.iter()
-        .map(|k| {
-            let val = new_state.get(*k).or_else(|| old_state.get(*k)).unwrap();
-            hash_kv(k, val)
-        })
-        .collect();
+    true
+}
+
+/// Runs in its own thread and periodically collapses every historical patch
+/// older than a configurable `min_retained_version` into the base snapshot,
+/// reclaiming memory for stale intermediate nodes while leaving recent
+/// versions fully provable.
+pub struct Pruner {
+    min_retained_version: Arc<AtomicU64>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
 
-    let calculated_root_res = proof.pre_state_proof.root(
-        &proof.affected_indices,
-        &new_leaves,
-        proof.total_leaves_old
-    );
+impl Pruner {
+    /// Spawns a background thread that calls [`VersionHistory::prune_to`]
+    /// every `interval`, using whatever `min_retained_version` was last set
+    /// via [`Self::set_min_retained_version`] (nothing is pruned until then).
+    pub fn spawn(db: VerifiableDB, interval: Duration) -> Self {
+        let min_retained_version = Arc::new(AtomicU64::new(0));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let min_retained_version = Arc::clone(&min_retained_version);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let target = min_retained_version.load(Ordering::Relaxed);
+                    if target > 0 {
+                        db.state.write().unwrap().history.prune_to(target);
+                    }
+                    thread::sleep(interval);
+                }
+            })
+        };
+
+        Self { min_retained_version, stop, handle: Some(handle) }
+    }
 
-    let calculated_root = match calculated_root_res {
-        Ok(r) => r,
-        Err(_) => return false,
-    };
+    pub fn set_min_retained_version(&self, version: u64) {
+        self.min_retained_version.store(version, Ordering::Relaxed);
+    }
+}
 
-    calculated_root == proof.new_root
+impl Drop for Pruner {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
 }
 
 #[cfg(test)]
@@ -192,7 +778,7 @@ mod tests {
 
     #[test]
     fn test_secure_update_transition() {
-        let store = VerifiableDB::new(true);
+        let store = VerifiableDB::in_memory(true);
 
         let mut t0 = store.begin();
         t0.put("alice", "100");
@@ -208,10 +794,10 @@ mod tests {
         let proof = txn.commit().unwrap();
 
         let mut old_state = HashMap::new();
-        old_state.insert("alice".to_string(), "100".to_string());
+        old_state.insert((DEFAULT_NAMESPACE.to_string(), "alice".to_string()), "100".to_string());
 
         let mut new_state = HashMap::new();
-        new_state.insert("alice".to_string(), "200".to_string());
+        new_state.insert((DEFAULT_NAMESPACE.to_string(), "alice".to_string()), "200".to_string());
 
         let is_valid = verify_secure_update(
             &proof,
@@ -224,7 +810,7 @@ mod tests {
 
     #[test]
     fn test_blind_write_is_covered() {
-        let store = VerifiableDB::new(true);
+        let store = VerifiableDB::in_memory(true);
 
         let mut t0 = store.begin();
         t0.put("x", "10");
@@ -236,12 +822,313 @@ mod tests {
         let proof = txn.commit().unwrap();
 
         let mut old_state = HashMap::new();
-        old_state.insert("x".to_string(), "10".to_string());
+        old_state.insert((DEFAULT_NAMESPACE.to_string(), "x".to_string()), "10".to_string());
 
         let mut new_state = HashMap::new();
-        new_state.insert("x".to_string(), "99".to_string());
+        new_state.insert((DEFAULT_NAMESPACE.to_string(), "x".to_string()), "99".to_string());
 
         let is_valid = verify_secure_update(&proof, &old_state, &new_state);
         assert!(is_valid);
     }
+
+    #[test]
+    fn test_blind_insert_proves_absence() {
+        let store = VerifiableDB::in_memory(true);
+
+        let mut t0 = store.begin();
+        t0.put("x", "10");
+        t0.commit();
+
+        let mut txn = store.begin();
+        txn.put("y", "new");
+        let proof = txn.commit().unwrap();
+
+        let old_state = HashMap::new();
+        let mut new_state = HashMap::new();
+        new_state.insert((DEFAULT_NAMESPACE.to_string(), "y".to_string()), "new".to_string());
+
+        assert!(verify_secure_update(&proof, &old_state, &new_state));
+    }
+
+    #[test]
+    fn test_secure_update_covers_two_keys_written_in_one_commit() {
+        // "alice" and "bob" inevitably share a Merkle-tree ancestor somewhere
+        // above the leaf level, so this exercises the case where an entry's
+        // sibling path changes between `old_version` and `new_version`
+        // because another entry in the *same* commit touched it.
+        let store = VerifiableDB::in_memory(true);
+
+        let mut t0 = store.begin();
+        t0.put("alice", "100");
+        t0.put("bob", "50");
+        t0.commit();
+
+        let mut txn = store.begin();
+        txn.put("alice", "200");
+        txn.put("bob", "60");
+        let proof = txn.commit().unwrap();
+        assert_eq!(proof.entries.len(), 2);
+
+        let mut old_state = HashMap::new();
+        old_state.insert((DEFAULT_NAMESPACE.to_string(), "alice".to_string()), "100".to_string());
+        old_state.insert((DEFAULT_NAMESPACE.to_string(), "bob".to_string()), "50".to_string());
+
+        let mut new_state = HashMap::new();
+        new_state.insert((DEFAULT_NAMESPACE.to_string(), "alice".to_string()), "200".to_string());
+        new_state.insert((DEFAULT_NAMESPACE.to_string(), "bob".to_string()), "60".to_string());
+
+        assert!(verify_secure_update(&proof, &old_state, &new_state));
+    }
+
+    #[test]
+    fn test_proof_at_historical_version() {
+        let store = VerifiableDB::in_memory(true);
+
+        let mut t0 = store.begin();
+        t0.put("alice", "100");
+        let proof0 = t0.commit().unwrap();
+
+        let mut t1 = store.begin();
+        t1.put("alice", "200");
+        t1.commit().unwrap();
+
+        assert_eq!(store.latest_version(), 2);
+
+        let historical = store.proof_at(proof0.new_version, "alice").unwrap();
+        assert_eq!(historical.root, proof0.new_root);
+        assert!(historical.is_valid());
+    }
+
+    #[test]
+    fn test_proof_at_returns_none_past_a_restarted_backends_history() {
+        let store = VerifiableDB::in_memory(true);
+
+        let mut t0 = store.begin();
+        t0.put("alice", "100");
+        let proof0 = t0.commit().unwrap();
+
+        let mut t1 = store.begin();
+        t1.put("alice", "200");
+        t1.commit().unwrap();
+
+        // Simulate a process restart: hand the backend (which, like RocksDB,
+        // only retains the latest snapshot) to a fresh `DB` with an empty
+        // `VersionHistory`, as `RocksDbDatabase::open` would produce.
+        let backend = {
+            let mut guard = store.state.write().unwrap();
+            std::mem::replace(&mut guard.backend, Box::new(MemoryDatabase::new()))
+        };
+        let restarted = VerifiableDB { state: Arc::new(RwLock::new(DB::new(backend))), verify_txn: true };
+
+        assert_eq!(restarted.latest_version(), 2);
+        assert!(restarted.proof_at(proof0.new_version, "alice").is_none());
+        assert!(restarted.proof_at(2, "alice").unwrap().is_valid());
+    }
+
+    #[test]
+    fn test_pruner_drops_versions_below_retained() {
+        let store = VerifiableDB::in_memory(true);
+
+        for i in 0..5 {
+            let mut txn = store.begin();
+            txn.put("alice", &i.to_string());
+            txn.commit();
+        }
+        assert_eq!(store.latest_version(), 5);
+
+        store.state.write().unwrap().history.prune_to(4);
+
+        assert!(store.proof_at(2, "alice").is_none());
+        let retained = store.proof_at(4, "alice").unwrap();
+        assert!(retained.is_valid());
+    }
+
+    #[test]
+    fn test_pruner_thread_prunes_and_joins_on_drop() {
+        let store = VerifiableDB::in_memory(true);
+
+        for i in 0..5 {
+            let mut txn = store.begin();
+            txn.put("alice", &i.to_string());
+            txn.commit();
+        }
+        assert_eq!(store.latest_version(), 5);
+
+        let pruner = Pruner::spawn(store.clone(), Duration::from_millis(10));
+        pruner.set_min_retained_version(4);
+        thread::sleep(Duration::from_millis(100));
+
+        assert!(store.proof_at(2, "alice").is_none());
+        let retained = store.proof_at(4, "alice").unwrap();
+        assert!(retained.is_valid());
+
+        drop(pruner);
+    }
+
+    #[test]
+    fn test_explicit_backend_survives_handle_clone() {
+        let backend = Box::new(MemoryDatabase::new());
+        let store = VerifiableDB::new(true, backend);
+
+        let mut txn = store.begin();
+        txn.put("k", "v");
+        txn.commit();
+
+        let cloned = store.clone();
+        assert_eq!(cloned.get_db_size(), 1);
+    }
+
+    #[test]
+    fn test_rollback_to_savepoint_discards_writes_and_reads() {
+        let store = VerifiableDB::in_memory(true);
+
+        let mut t0 = store.begin();
+        t0.put("alice", "100");
+        t0.commit();
+
+        let mut txn = store.begin();
+        txn.put("bob", "1");
+
+        let sp = txn.savepoint();
+        txn.get("alice");
+        txn.put("bob", "2");
+        txn.put("carol", "3");
+        txn.rollback_to(sp);
+
+        let proof = txn.commit().unwrap();
+
+        // "alice" was only read inside the rolled-back savepoint, so it must
+        // not appear in the final read set, and "bob"/"carol" must reflect
+        // only the writes made before the savepoint.
+        assert!(!proof.entries.iter().any(|e| e.key == "alice"));
+        assert_eq!(store.get_db_size(), 2);
+
+        let mut check = store.begin();
+        assert_eq!(check.get("bob"), Some("1".to_string()));
+        assert_eq!(check.get("carol"), None);
+    }
+
+    #[test]
+    fn test_release_savepoint_keeps_writes() {
+        let store = VerifiableDB::in_memory(true);
+
+        let mut txn = store.begin();
+        let sp = txn.savepoint();
+        txn.put("alice", "100");
+        txn.release(sp);
+        txn.commit();
+
+        let mut check = store.begin();
+        assert_eq!(check.get("alice"), Some("100".to_string()));
+    }
+
+    #[test]
+    fn test_namespaces_are_isolated_and_proof_chains_through_top_level() {
+        let store = VerifiableDB::in_memory(true);
+
+        let mut t0 = store.begin();
+        t0.put_in("acct1", "balance", "100");
+        t0.put_in("acct2", "balance", "5");
+        let proof0 = t0.commit().unwrap();
+
+        // Same key in two namespaces must not alias.
+        let mut check = store.begin();
+        assert_eq!(check.get_in("acct1", "balance"), Some("100".to_string()));
+        assert_eq!(check.get_in("acct2", "balance"), Some("5".to_string()));
+        drop(check);
+
+        assert_eq!(proof0.entries.len(), 2);
+        for entry in &proof0.entries {
+            assert_eq!(entry.old_leaf, EMPTY_LEAF);
+            assert_eq!(entry.kind, EntryKind::Insert);
+        }
+
+        let mut t1 = store.begin();
+        t1.put_in("acct1", "balance", "200");
+        let proof1 = t1.commit().unwrap();
+
+        let mut old_state = HashMap::new();
+        old_state.insert(("acct1".to_string(), "balance".to_string()), "100".to_string());
+        let mut new_state = HashMap::new();
+        new_state.insert(("acct1".to_string(), "balance".to_string()), "200".to_string());
+        assert!(verify_secure_update(&proof1, &old_state, &new_state));
+
+        let historical = store.proof_at_in(proof0.new_version, "acct2", "balance").unwrap();
+        assert_eq!(historical.root, proof0.new_root);
+        assert!(historical.is_valid());
+    }
+
+    #[test]
+    fn test_secure_update_distinguishes_same_key_across_namespaces() {
+        // A transfer updating "balance" in both "acct1" and "acct2" in one
+        // commit: the two entries share a key name, so `old_state`/`new_state`
+        // must be keyed by (namespace, key) or one account's claimed value
+        // would be forced onto the other.
+        let store = VerifiableDB::in_memory(true);
+
+        let mut t0 = store.begin();
+        t0.put_in("acct1", "balance", "100");
+        t0.put_in("acct2", "balance", "5");
+        t0.commit();
+
+        let mut txn = store.begin();
+        txn.put_in("acct1", "balance", "90");
+        txn.put_in("acct2", "balance", "15");
+        let proof = txn.commit().unwrap();
+        assert_eq!(proof.entries.len(), 2);
+
+        let mut old_state = HashMap::new();
+        old_state.insert(("acct1".to_string(), "balance".to_string()), "100".to_string());
+        old_state.insert(("acct2".to_string(), "balance".to_string()), "5".to_string());
+
+        let mut new_state = HashMap::new();
+        new_state.insert(("acct1".to_string(), "balance".to_string()), "90".to_string());
+        new_state.insert(("acct2".to_string(), "balance".to_string()), "15".to_string());
+
+        assert!(verify_secure_update(&proof, &old_state, &new_state));
+    }
+
+    #[test]
+    fn test_prove_membership_and_absence() {
+        let store = VerifiableDB::in_memory(true);
+
+        assert!(store.prove_membership("alice").is_none());
+
+        let mut t0 = store.begin();
+        t0.put("alice", "100");
+        t0.commit();
+
+        assert!(store.prove_absence("alice").is_none());
+        let membership = store.prove_membership("alice").unwrap();
+        assert!(membership.is_valid());
+        assert_eq!(membership.leaf, hash_kv("alice", "100"));
+
+        assert!(store.prove_membership("bob").is_none());
+        let absence = store.prove_absence("bob").unwrap();
+        assert!(absence.is_valid());
+        assert_eq!(absence.leaf, EMPTY_LEAF);
+        assert_eq!(absence.root, membership.root);
+    }
+
+    #[test]
+    fn test_verify_secure_update_rejects_kind_mismatch() {
+        let store = VerifiableDB::in_memory(true);
+
+        let mut t0 = store.begin();
+        t0.put("alice", "100");
+        t0.commit();
+
+        let mut txn = store.begin();
+        txn.put("alice", "200");
+        let proof = txn.commit().unwrap();
+        assert_eq!(proof.entries[0].kind, EntryKind::Overwrite);
+
+        // Claiming "alice" was absent beforehand contradicts the proof's
+        // `Overwrite` kind, even though the leaf hash check alone wouldn't
+        // catch every such mismatch.
+        let old_state = HashMap::new();
+        let mut new_state = HashMap::new();
+        new_state.insert((DEFAULT_NAMESPACE.to_string(), "alice".to_string()), "200".to_string());
+        assert!(!verify_secure_update(&proof, &old_state, &new_state));
+    }
 }
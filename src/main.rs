@@ -8,14 +8,16 @@ use rand::prelude::*;
 fn run_experiments(verify: bool, num_txns: u32, key_space_size: u64) -> usize {
   let mut rng = rand::rng();
 
-  let db = VerifiableDB::new(verify);
+  let db = VerifiableDB::in_memory(verify);
   for _ in 1..num_txns {
     // if i % 1000 == 0 {
     //   println!("{}", i)
     // }
     let mut txn = db.begin();
     for _ in 1..100 {
-      txn.put(rng.random::<u64>() % key_space_size, rng.random::<u64>() % key_space_size);
+      let key = (rng.random::<u64>() % key_space_size).to_string();
+      let value = (rng.random::<u64>() % key_space_size).to_string();
+      txn.put(&key, &value);
     }
     txn.commit();
   }
@@ -0,0 +1,42 @@
+use super::{Database, NodeKey, PatchSet};
+use crate::Hash;
+use std::collections::HashMap;
+
+/// The original in-memory backend: every value and tree node lives in a
+/// `HashMap` and is lost when the process exits.
+#[derive(Default)]
+pub struct MemoryDatabase {
+    values: HashMap<String, String>,
+    nodes: HashMap<NodeKey, Hash>,
+    version: u64,
+}
+
+impl MemoryDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Database for MemoryDatabase {
+    fn apply_patch(&mut self, patch: PatchSet) {
+        self.values.extend(patch.values);
+        self.nodes.extend(patch.nodes);
+        self.version = patch.version;
+    }
+
+    fn get_node(&self, key: NodeKey) -> Option<Hash> {
+        self.nodes.get(&key).copied()
+    }
+
+    fn get_value(&self, key: &str) -> Option<String> {
+        self.values.get(key).cloned()
+    }
+
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    fn current_version(&self) -> u64 {
+        self.version
+    }
+}
@@ -0,0 +1,34 @@
+use crate::{Hash, TreeId};
+use std::collections::HashMap;
+
+mod memory;
+mod rocksdb_backend;
+
+pub use memory::MemoryDatabase;
+pub use rocksdb_backend::RocksDbDatabase;
+
+/// Identifies one Merkle tree node: which tree it belongs to (the top-level
+/// tree of namespace roots, or one namespace's own subtree), its height
+/// above the leaves, and the level-masked path prefix that addresses it.
+pub type NodeKey = (TreeId, u16, Hash);
+
+/// Everything one committed `Transaction` changed: the key/value writes and
+/// the tree nodes rehashed along their authentication paths.
+pub struct PatchSet {
+    pub version: u64,
+    pub values: HashMap<String, String>,
+    pub nodes: HashMap<NodeKey, Hash>,
+}
+
+/// A storage backend for `VerifiableDB`. Each commit hands over exactly one
+/// `PatchSet`, which an implementation should apply atomically.
+pub trait Database: Send + Sync {
+    fn apply_patch(&mut self, patch: PatchSet);
+    fn get_node(&self, key: NodeKey) -> Option<Hash>;
+    fn get_value(&self, key: &str) -> Option<String>;
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn current_version(&self) -> u64;
+}